@@ -18,10 +18,33 @@ Sentry.init({
     tracesSampleRate: 1.0,
 });
 
+// Keep below pm2's --kill-timeout in package.json so shutdown finishes before SIGKILL.
+const SHUTDOWN_GRACE_MS = 8000;
+
+// Origins allowed cross-origin from ALLOWED_ORIGINS (comma-separated), any origin only in development.
+const allowedOrigins = () => {
+    const origins = (process.env.ALLOWED_ORIGINS || '')
+        .split(',')
+        .map(origin => origin.trim())
+        .filter(Boolean);
+
+    if (origins.length)
+        return origins;
+
+    if (process.env.NODE_ENV === 'development')
+        return '*';
+
+    // Without it every cross-origin request, including the website's own frontend, would be refused.
+    throw new Error('ALLOWED_ORIGINS must be set unless NODE_ENV is development.');
+};
+
 export default async function api() {
     // Load secrets.
     await secrets();
 
+    // Fail before connecting anything if CORS is not configured, see README.md.
+    const corsOrigin = allowedOrigins();
+
     // Connect to PostGres Database and attach event/error handlers.
     await Database.connect();
 
@@ -32,8 +55,8 @@ export default async function api() {
     app.use(BodyParser.urlencoded({ extended: false }));
     app.use(BodyParser.json());
 
-    // Disable security, tighten "later".
-    app.use(cors({ origin: '*' }));
+    // Restrict cross-origin access to the configured origins.
+    app.use(cors({ origin: corsOrigin }));
 
     // Add authentication strategy for protected routes/data.
     passport.use(Auth.strategy());
@@ -50,6 +73,51 @@ export default async function api() {
     // Start listening on the app.
     server.listen(3000);
     console.log('API listening, port: ' + 3000);
+
+    // Track in-flight requests per socket so idle keep-alive connections can be closed on shutdown.
+    const sockets = new Map();
+    let shuttingDown = false;
+    server.on('connection', socket => {
+        sockets.set(socket, 0);
+        socket.once('close', () => sockets.delete(socket));
+    });
+    server.on('request', (req, res) => {
+        const socket = req.socket;
+        sockets.set(socket, sockets.get(socket) + 1);
+        res.once('finish', () => {
+            if (!sockets.has(socket)) return;
+            sockets.set(socket, sockets.get(socket) - 1);
+
+            // Once shutting down, close each connection as soon as its last request is answered.
+            if (shuttingDown && sockets.get(socket) === 0)
+                socket.end();
+        });
+    });
+
+    // Stop accepting connections on restart/deploy and let in-flight requests finish.
+    const shutdown = signal => {
+        console.log('API shutting down, signal: ' + signal);
+        shuttingDown = true;
+        server.close(() => process.exit(0));
+
+        // Node 16 leaves idle keep-alive sockets open until they time out, so close them here.
+        sockets.forEach((inFlight, socket) => {
+            if (!inFlight) socket.destroy();
+        });
+
+        // Routine behind a proxy, not a failure, so still exit cleanly.
+        setTimeout(() => {
+            console.log('Force-closing lingering connections: ' + sockets.size);
+            sockets.forEach((inFlight, socket) => socket.destroy());
+            process.exit(0);
+        }, SHUTDOWN_GRACE_MS).unref();
+    };
+    process.once('SIGTERM', shutdown);
+    process.once('SIGINT', shutdown);
 };
 
-api();
+api().catch(e => {
+    console.log('API failed to start.');
+    console.error(e);
+    process.exit(1);
+});